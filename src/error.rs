@@ -2,85 +2,307 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use failure::{Backtrace, Context, Fail};
+use std::error::Error as StdError;
 use std::fmt::{self, Display};
 
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+
 /// The different kinds of errors that can be returned
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 #[non_exhaustive]
 pub enum RustbreakErrorKind {
     /// A context error when a serialization failed
-    #[fail(display = "Could not serialize the value")]
     Serialization,
     /// A context error when a deserialization failed
-    #[fail(display = "Could not deserialize the value")]
     Deserialization,
     /// This error is returned if the `Database` is poisoned. See
     /// `Database::write` for details
-    #[fail(display = "The database has been poisoned")]
     Poison,
     /// An error in the backend happened
-    #[fail(display = "The backend has encountered an error")]
     Backend,
     /// If `Database::write_safe` is used and the closure panics, this error is
     /// returned
-    #[fail(display = "The write operation paniced but got caught")]
     WritePanic,
 }
 
+impl Display for RustbreakErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self {
+            Self::Serialization => "Could not serialize the value",
+            Self::Deserialization => "Could not deserialize the value",
+            Self::Poison => "The database has been poisoned",
+            Self::Backend => "The backend has encountered an error",
+            Self::WritePanic => "The write operation paniced but got caught",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl RustbreakErrorKind {
+    /// A stable, machine-readable code identifying this kind of error.
+    ///
+    /// Unlike the `Display` message, this value is part of the crate's
+    /// public API contract: it will not change across releases, so it is
+    /// safe to key dashboards, alerts or metrics off of it.
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Serialization => "rustbreak.serialization",
+            Self::Deserialization => "rustbreak.deserialization",
+            Self::Poison => "rustbreak.poison",
+            Self::Backend => "rustbreak.backend",
+            Self::WritePanic => "rustbreak.write_panic",
+        }
+    }
+}
+
 /// The main error type that gets returned for errors that happen while
 /// interacting with a `Database`.
 #[derive(Debug)]
 pub struct RustbreakError {
-    inner: Context<RustbreakErrorKind>,
+    kind: RustbreakErrorKind,
+    cause: Option<Box<dyn StdError + Send + Sync + 'static>>,
+    #[cfg(feature = "backtrace")]
+    backtrace: Backtrace,
 }
 
-impl Fail for RustbreakError {
-    fn cause(&self) -> Option<&dyn Fail> {
-        self.inner.cause()
-    }
-
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.inner.backtrace()
+impl StdError for RustbreakError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.cause
+            .as_ref()
+            .map(|cause| cause.as_ref() as &(dyn StdError + 'static))
     }
 }
 
 impl Display for RustbreakError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        Display::fmt(&self.inner, f)
+        if f.alternate() {
+            f.write_str(&self.report())
+        } else {
+            Display::fmt(&self.kind, f)
+        }
     }
 }
 
 impl RustbreakError {
     /// Get the kind of this error
     pub fn kind(&self) -> RustbreakErrorKind {
-        *self.inner.get_context()
+        self.kind
+    }
+
+    /// The stable, machine-readable code of this error's kind. See
+    /// [`RustbreakErrorKind::code`].
+    #[must_use]
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    /// Wrap `kind` around the concrete error that caused it, preserving it
+    /// so it can later be recovered with [`downcast_ref`](Self::downcast_ref)
+    /// or [`source_kind`](Self::source_kind).
+    pub fn with_source<E>(kind: RustbreakErrorKind, source: E) -> Self
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Self {
+            kind,
+            cause: Some(Box::new(source)),
+            #[cfg(feature = "backtrace")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    /// Attempt to downcast the wrapped cause to a concrete error type, e.g.
+    /// to recover the original `io::Error` behind a `Backend` error.
+    ///
+    /// This walks the entire `source()` chain, so it still finds the
+    /// original error even when `.context(...)` calls have been layered on
+    /// top of it.
+    pub fn downcast_ref<E: StdError + 'static>(&self) -> Option<&E> {
+        let mut cause = self
+            .cause
+            .as_deref()
+            .map(|c| c as &(dyn StdError + 'static));
+        while let Some(err) = cause {
+            if let Some(found) = err.downcast_ref::<E>() {
+                return Some(found);
+            }
+            cause = err.source();
+        }
+        None
+    }
+
+    /// Access the wrapped cause as a trait object, without needing to
+    /// `use std::error::Error` to call [`source`](StdError::source).
+    pub fn source_kind(&self) -> Option<&(dyn StdError + 'static)> {
+        self.cause
+            .as_deref()
+            .map(|cause| cause as &(dyn StdError + 'static))
+    }
+
+    /// Get the backtrace captured when this error was created, if the
+    /// `backtrace` feature is enabled.
+    #[cfg(feature = "backtrace")]
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// Render this error together with its full `source()` chain and, when
+    /// captured, a backtrace — one "Caused by:" line per cause, much like
+    /// anyhow's `{:#}` output. The single-line [`Display`] form (`{}`) is
+    /// unaffected and keeps showing only this error's own message.
+    #[must_use]
+    pub fn report(&self) -> String {
+        let mut out = self.kind.to_string();
+
+        let mut cause = StdError::source(self);
+        while let Some(err) = cause {
+            out.push_str("\n\nCaused by:\n    ");
+            out.push_str(&err.to_string());
+            cause = err.source();
+        }
+
+        #[cfg(feature = "backtrace")]
+        if self.backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+            out.push_str("\n\nBacktrace:\n");
+            out.push_str(&self.backtrace.to_string());
+        } else {
+            out.push_str("\n\nrun with `RUST_BACKTRACE=1` to capture a backtrace");
+        }
+        #[cfg(not(feature = "backtrace"))]
+        out.push_str(
+            "\n\nrun with the `backtrace` feature and `RUST_BACKTRACE=1` to capture a backtrace",
+        );
+
+        out
     }
 }
 
 impl From<RustbreakErrorKind> for RustbreakError {
     fn from(kind: RustbreakErrorKind) -> Self {
         Self {
-            inner: Context::new(kind),
+            kind,
+            cause: None,
+            #[cfg(feature = "backtrace")]
+            backtrace: Backtrace::capture(),
         }
     }
 }
 
-impl From<Context<RustbreakErrorKind>> for RustbreakError {
-    fn from(inner: Context<RustbreakErrorKind>) -> Self {
-        Self { inner }
+/// A simple type alias for errors
+pub type Result<T> = std::result::Result<T, RustbreakError>;
+
+/// A human-readable annotation attached to an error by [`ResultExt::context`].
+///
+/// It splices into the *cause* chain that was already hanging off the
+/// error it annotates, rather than wrapping the whole error (which would
+/// re-emit that error's kind message as a redundant chain link).
+struct ContextError<C> {
+    context: C,
+    cause: Option<Box<dyn StdError + Send + Sync + 'static>>,
+}
+
+impl<C: Display> fmt::Debug for ContextError<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.context, f)
     }
 }
 
-/// A simple type alias for errors
-pub type Result<T> = std::result::Result<T, RustbreakError>;
+impl<C: Display> Display for ContextError<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.context, f)
+    }
+}
+
+impl<C: Display> StdError for ContextError<C> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.cause
+            .as_deref()
+            .map(|cause| cause as &(dyn StdError + 'static))
+    }
+}
+
+/// Extension trait for attaching human-readable context to a failing
+/// [`Result`], in the spirit of `anyhow::Context`.
+///
+/// The context message becomes the next link in the error's cause chain (see
+/// [`RustbreakError::report`]), while [`RustbreakError::kind`] keeps
+/// reflecting the original failure.
+pub trait ResultExt<T> {
+    /// Annotate the error, if any, with `context`.
+    fn context<C>(self, context: C) -> Result<T>
+    where
+        C: Display + Send + Sync + 'static;
+
+    /// Annotate the error, if any, with a lazily computed `context`.
+    ///
+    /// Use this over [`context`](Self::context) when building the message
+    /// isn't free, since it is only evaluated on the error path.
+    fn with_context<C, F>(self, context: F) -> Result<T>
+    where
+        C: Display + Send + Sync + 'static,
+        F: FnOnce() -> C;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn context<C>(self, context: C) -> Result<T>
+    where
+        C: Display + Send + Sync + 'static,
+    {
+        self.with_context(|| context)
+    }
+
+    fn with_context<C, F>(self, context: F) -> Result<T>
+    where
+        C: Display + Send + Sync + 'static,
+        F: FnOnce() -> C,
+    {
+        self.map_err(|err| {
+            let kind = err.kind;
+            let cause = err.cause;
+            // Keep the backtrace captured at the original failure site
+            // (`with_source`/`From<RustbreakErrorKind>`) rather than
+            // recapturing it here, where it would only show the
+            // `.context()` call site.
+            #[cfg(feature = "backtrace")]
+            let backtrace = err.backtrace;
+
+            RustbreakError {
+                kind,
+                cause: Some(Box::new(ContextError {
+                    context: context(),
+                    cause,
+                })),
+                #[cfg(feature = "backtrace")]
+                backtrace,
+            }
+        })
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use super::{RustbreakError, RustbreakErrorKind};
-    use failure::Context;
+    use super::{ResultExt, RustbreakError, RustbreakErrorKind};
     use std::any::Any;
 
+    /// Asserts that `report` starts with `expected_chain` (the kind message
+    /// plus its "Caused by:" lines) and that whatever `report()` appends
+    /// after it — a captured backtrace or the `RUST_BACKTRACE=1` hint,
+    /// depending on the `backtrace` feature and environment — is a separate
+    /// section rather than more chain content.
+    fn assert_cause_chain(report: &str, expected_chain: &str) {
+        assert!(
+            report.starts_with(expected_chain),
+            "report {report:?} does not start with the expected cause chain {expected_chain:?}"
+        );
+        let rest = &report[expected_chain.len()..];
+        assert!(
+            rest.is_empty() || rest.starts_with("\n\n"),
+            "unexpected trailing content after the cause chain: {rest:?}"
+        );
+    }
+
     #[test]
     fn static_errorkind_impl_any() {
         let err = RustbreakErrorKind::Backend;
@@ -90,9 +312,159 @@ mod tests {
 
     #[test]
     fn static_error_impl_any() {
-        let context = RustbreakErrorKind::Serialization;
-        let err: RustbreakError = Context::new(context).into();
+        let err: RustbreakError = RustbreakErrorKind::Serialization.into();
         let boxed: Box<dyn Any> = Box::new(err);
         assert!(boxed.is::<RustbreakError>());
     }
+
+    #[test]
+    fn downcast_ref_recovers_concrete_cause() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "missing file");
+        let err = RustbreakError::with_source(RustbreakErrorKind::Backend, io_err);
+
+        let recovered = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(recovered.kind(), std::io::ErrorKind::NotFound);
+        assert!(err.downcast_ref::<std::fmt::Error>().is_none());
+    }
+
+    #[test]
+    fn downcast_ref_sees_past_context_layers() {
+        let io_err = std::io::Error::new(std::io::ErrorKind::NotFound, "missing file");
+        let err: Result<(), RustbreakError> = Err(RustbreakError::with_source(
+            RustbreakErrorKind::Backend,
+            io_err,
+        ));
+        let err = err
+            .context("writing database to /var/lib/app.db")
+            .unwrap_err();
+
+        let recovered = err.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(recovered.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn context_chains_message_and_keeps_kind() {
+        let result: Result<(), RustbreakError> = Err(RustbreakErrorKind::Backend.into());
+        let err = result
+            .context("writing database to /var/lib/app.db")
+            .unwrap_err();
+
+        assert_eq!(err.kind(), RustbreakErrorKind::Backend);
+        let source = std::error::Error::source(&err).unwrap();
+        assert_eq!(source.to_string(), "writing database to /var/lib/app.db");
+        assert!(source.source().is_none());
+    }
+
+    #[test]
+    fn stacked_context_does_not_duplicate_the_kind_message() {
+        let result: Result<(), RustbreakError> = Err(RustbreakErrorKind::Backend.into());
+        let err = result
+            .context("writing database to /var/lib/app.db")
+            .context("handling save request")
+            .unwrap_err();
+
+        assert_eq!(err.kind(), RustbreakErrorKind::Backend);
+        assert_cause_chain(
+            &err.report(),
+            "The backend has encountered an error\n\n\
+             Caused by:\n    handling save request\n\n\
+             Caused by:\n    writing database to /var/lib/app.db",
+        );
+    }
+
+    #[test]
+    fn with_context_is_not_evaluated_on_the_ok_path() {
+        let result: Result<(), RustbreakError> = Ok(());
+        let mut called = false;
+        result
+            .with_context(|| {
+                called = true;
+                "deserializing user record 42"
+            })
+            .unwrap();
+
+        assert!(!called);
+    }
+
+    #[test]
+    fn code_is_stable_per_kind() {
+        assert_eq!(
+            RustbreakErrorKind::Serialization.code(),
+            "rustbreak.serialization"
+        );
+        assert_eq!(RustbreakErrorKind::Poison.code(), "rustbreak.poison");
+
+        let err: RustbreakError = RustbreakErrorKind::Backend.into();
+        assert_eq!(err.code(), "rustbreak.backend");
+    }
+
+    #[test]
+    fn default_display_is_unchanged_by_report() {
+        let result: Result<(), RustbreakError> = Err(RustbreakErrorKind::Backend.into());
+        let err = result
+            .context("writing database to /var/lib/app.db")
+            .unwrap_err();
+
+        assert_eq!(err.to_string(), "The backend has encountered an error");
+    }
+
+    #[test]
+    fn report_walks_the_full_cause_chain() {
+        let result: Result<(), RustbreakError> = Err(RustbreakErrorKind::Backend.into());
+        let err = result
+            .context("writing database to /var/lib/app.db")
+            .unwrap_err();
+
+        let report = err.report();
+        assert_cause_chain(
+            &report,
+            "The backend has encountered an error\n\n\
+             Caused by:\n    writing database to /var/lib/app.db",
+        );
+
+        assert_eq!(format!("{err:#}"), report);
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn context_preserves_the_backtrace_from_the_original_failure() {
+        // SAFETY: tests in this module run single-threaded w.r.t. this var.
+        unsafe { std::env::set_var("RUST_BACKTRACE", "1") }
+
+        let original: RustbreakError = RustbreakErrorKind::Backend.into();
+        let original_backtrace = original.backtrace().to_string();
+
+        let annotated = Err::<(), _>(original)
+            .context("writing database to /var/lib/app.db")
+            .context("handling save request")
+            .unwrap_err();
+
+        assert_eq!(annotated.backtrace().to_string(), original_backtrace);
+    }
+
+    #[cfg(feature = "backtrace")]
+    #[test]
+    fn report_cause_chain_is_stable_regardless_of_backtrace_capture() {
+        use std::backtrace::Backtrace;
+
+        // `Backtrace::capture` caches its reading of `RUST_BACKTRACE` after
+        // the first call, so build a disabled and a captured backtrace
+        // explicitly instead of toggling the variable.
+        for backtrace in [Backtrace::disabled(), Backtrace::force_capture()] {
+            let result: Result<(), RustbreakError> = Err(RustbreakError {
+                kind: RustbreakErrorKind::Backend,
+                cause: None,
+                backtrace,
+            });
+            let err = result
+                .context("writing database to /var/lib/app.db")
+                .unwrap_err();
+
+            assert_cause_chain(
+                &err.report(),
+                "The backend has encountered an error\n\n\
+                 Caused by:\n    writing database to /var/lib/app.db",
+            );
+        }
+    }
 }